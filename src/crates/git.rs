@@ -0,0 +1,347 @@
+use crate::crates::CrateTrait;
+use crate::Workspace;
+use failure::{bail, format_err, Error};
+use log::info;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+/// How to authenticate with a private git repository when fetching it.
+pub enum GitAuth {
+    /// Authenticate over SSH with a private key, optionally protected by a passphrase.
+    Ssh {
+        key: PathBuf,
+        passphrase: Option<String>,
+    },
+    /// Authenticate over HTTPS with a username and a password or access token.
+    Https { username: String, token: String },
+}
+
+impl GitAuth {
+    /// Authenticate with an SSH private key at `key`, optionally protected by `passphrase`.
+    pub fn ssh_key(key: impl Into<PathBuf>, passphrase: Option<&str>) -> Self {
+        GitAuth::Ssh {
+            key: key.into(),
+            passphrase: passphrase.map(|p| p.to_string()),
+        }
+    }
+
+    /// Authenticate over HTTPS with a username and a password or access token.
+    pub fn https(username: &str, token: &str) -> Self {
+        GitAuth::Https {
+            username: username.to_string(),
+            token: token.to_string(),
+        }
+    }
+}
+
+pub(super) struct GitRepo {
+    pub(super) url: String,
+    pub(super) name: String,
+    branch: Option<String>,
+    rev: Option<String>,
+    auth: Option<GitAuth>,
+    submodules: bool,
+    // A `Mutex` rather than a `RefCell`: `GitRepo` needs to be `Sync` so crates can be fetched
+    // concurrently (see `Workspace::fetch_all`).
+    resolved_commit: Mutex<Option<String>>,
+}
+
+impl GitRepo {
+    pub(super) fn new(url: &str, name: &str) -> Self {
+        GitRepo {
+            url: url.to_string(),
+            name: name.to_string(),
+            branch: None,
+            rev: None,
+            auth: None,
+            submodules: false,
+            resolved_commit: Mutex::new(None),
+        }
+    }
+
+    pub(super) fn branch(mut self, branch: Option<&str>) -> Self {
+        self.branch = branch.map(|b| b.to_string());
+        self
+    }
+
+    /// Pin the repository to a specific revision or tag, rather than tracking the tip of a
+    /// branch. The rev can be a full or abbreviated commit SHA, or a tag name.
+    pub(super) fn rev(mut self, rev: Option<&str>) -> Self {
+        self.rev = rev.map(|r| r.to_string());
+        self
+    }
+
+    /// Authenticate with the remote repository using the given credentials, for private repos
+    /// that can't be fetched anonymously.
+    pub(super) fn auth(mut self, auth: GitAuth) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Whether to recursively fetch and populate git submodules. Defaults to `false`, to
+    /// preserve the old behavior of leaving submodule directories empty.
+    pub(super) fn submodules(mut self, submodules: bool) -> Self {
+        self.submodules = submodules;
+        self
+    }
+
+    fn cache_path(&self, workspace: &Workspace) -> PathBuf {
+        workspace.cache_dir().join(self.cache_key())
+    }
+
+    /// Build the [`AuthEnv`] to use for git invocations, retrying over SSH-agent, a provided
+    /// key, or a username/token pair as configured. The URL itself is never rewritten to embed
+    /// credentials: that would leak them to any local user through `ps`/`/proc/<pid>/cmdline`
+    /// for the duration of the clone.
+    fn auth_env(&self) -> Result<AuthEnv, Error> {
+        match &self.auth {
+            None => Ok(AuthEnv::default()),
+            Some(GitAuth::Https { username, token }) => {
+                if !self.url.starts_with("https://") {
+                    bail!(
+                        "HTTPS authentication requires an https:// URL, got {}",
+                        self.url
+                    );
+                }
+                AuthEnv::for_https(username, token)
+            }
+            Some(GitAuth::Ssh { key, passphrase }) => {
+                AuthEnv::for_ssh_key(key, passphrase.as_deref())
+            }
+        }
+    }
+
+    pub(super) fn git_commit(&self, workspace: &Workspace) -> Option<String> {
+        if let Some(resolved) = self.resolved_commit.lock().unwrap().as_ref() {
+            return Some(resolved.clone());
+        }
+        rev_parse_head(&self.cache_path(workspace))
+    }
+}
+
+impl CrateTrait for GitRepo {
+    fn cache_key(&self) -> String {
+        format!("git-repos/{}", self.name)
+    }
+
+    fn fetch(&self, workspace: &Workspace) -> Result<(), Error> {
+        let dest = self.cache_path(workspace);
+        let env = self.auth_env()?;
+
+        if dest.join(".git").is_dir() {
+            info!("updating cached repository {}", self.url);
+            run_git(&dest, &["fetch", "--tags", "origin"], &env)?;
+            let target = self
+                .branch
+                .as_deref()
+                .map(|branch| format!("origin/{}", branch))
+                .unwrap_or_else(|| "origin/HEAD".to_string());
+            run_git(&dest, &["reset", "--hard", &target], &env)?;
+        } else {
+            info!("cloning repository {}", self.url);
+            if let Some(parent) = dest.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            let dest_str = dest
+                .to_str()
+                .ok_or_else(|| format_err!("the cache path contains invalid UTF-8"))?;
+            let mut args = vec!["clone", self.url.as_str()];
+            if let Some(branch) = &self.branch {
+                args.extend(&["--branch", branch]);
+            }
+            args.push(dest_str);
+            run_git(Path::new("."), &args, &env)?;
+        }
+
+        if let Some(rev) = &self.rev {
+            info!("checking out {} at {}", self.url, rev);
+            run_git(&dest, &["checkout", rev], &env)
+                .map_err(|_| format_err!("revision {} not found in {}", rev, self.url))?;
+            let resolved = rev_parse_head(&dest)
+                .ok_or_else(|| format_err!("failed to resolve revision {} in {}", rev, self.url))?;
+            *self.resolved_commit.lock().unwrap() = Some(resolved);
+        }
+
+        if self.submodules {
+            info!("updating submodules of {}", self.url);
+            run_git(
+                &dest,
+                &["submodule", "update", "--init", "--recursive"],
+                &env,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    fn purge_from_cache(&self, workspace: &Workspace) -> Result<(), Error> {
+        let dest = self.cache_path(workspace);
+        if dest.exists() {
+            crate::utils::remove_dir_all(&dest)?;
+        }
+        Ok(())
+    }
+
+    fn copy_source_to(&self, workspace: &Workspace, dest: &Path) -> Result<(), Error> {
+        crate::utils::copy_dir_all(&self.cache_path(workspace), dest)
+    }
+
+    fn cached_at(&self, workspace: &Workspace) -> Option<std::time::SystemTime> {
+        // FETCH_HEAD is rewritten by every `git fetch`, so its mtime marks when this repository
+        // was last synced with its remote. A fresh `git clone` doesn't write FETCH_HEAD, so fall
+        // back to HEAD, which the clone creates.
+        let git_dir = self.cache_path(workspace).join(".git");
+        fs::metadata(git_dir.join("FETCH_HEAD"))
+            .or_else(|_| fs::metadata(git_dir.join("HEAD")))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+}
+
+impl fmt::Display for GitRepo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "git repo {}", self.url)
+    }
+}
+
+fn rev_parse_head(repo: &Path) -> Option<String> {
+    let output = Command::new("git")
+        .args(&["rev-parse", "HEAD"])
+        .current_dir(repo)
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout)
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+fn run_git(dir: &Path, args: &[&str], env: &AuthEnv) -> Result<(), Error> {
+    let mut command = Command::new("git");
+    command.args(args).current_dir(dir);
+    for (key, value) in &env.vars {
+        command.env(key, value);
+    }
+    let status = command.status()?;
+    if !status.success() {
+        bail!("command `git {}` failed with {}", args.join(" "), status);
+    }
+    Ok(())
+}
+
+/// Environment variables needed to authenticate a `git` invocation, plus the lifetime of any
+/// supporting files (e.g. an askpass helper) those variables point at.
+#[derive(Default)]
+struct AuthEnv {
+    vars: Vec<(String, String)>,
+    // Kept alive for as long as `vars` may reference it; deleted on drop.
+    _askpass_script: Option<TempFile>,
+}
+
+impl AuthEnv {
+    /// Authenticate over SSH, retrying over the agent first and then falling back to the
+    /// provided private key (decrypting it with `passphrase` through a throwaway askpass helper
+    /// if one was given), mirroring git2's credential callback retry order.
+    fn for_ssh_key(key: &Path, passphrase: Option<&str>) -> Result<Self, Error> {
+        let mut vars = vec![(
+            "GIT_SSH_COMMAND".to_string(),
+            format!(
+                "ssh -i {} -o IdentitiesOnly=no -o StrictHostKeyChecking=accept-new",
+                shell_quote(key)
+            ),
+        )];
+
+        let askpass_script = if let Some(passphrase) = passphrase {
+            let script = create_askpass_script(&format!(
+                "#!/bin/sh\nprintf '%s\\n' {}\n",
+                shell_quote_str(passphrase)
+            ))?;
+            vars.push(("SSH_ASKPASS".to_string(), script.path.display().to_string()));
+            vars.push(("SSH_ASKPASS_REQUIRE".to_string(), "force".to_string()));
+            Some(script)
+        } else {
+            None
+        };
+
+        Ok(AuthEnv {
+            vars,
+            _askpass_script: askpass_script,
+        })
+    }
+
+    /// Authenticate over HTTPS with a username and a password/token, supplied to git through a
+    /// throwaway `GIT_ASKPASS` helper rather than embedded in the clone URL: the URL ends up as
+    /// a literal `git` argv element, which any local user can read for the duration of the clone
+    /// via `ps`/`/proc/<pid>/cmdline`.
+    fn for_https(username: &str, token: &str) -> Result<Self, Error> {
+        // `git` invokes the askpass helper once per credential prompt, passing the prompt text
+        // (e.g. "Username for 'https://example.com': ") as `$1`.
+        let script = create_askpass_script(&format!(
+            "#!/bin/sh\ncase \"$1\" in\n  Username*) printf '%s\\n' {} ;;\n  *) printf '%s\\n' {} ;;\nesac\n",
+            shell_quote_str(username),
+            shell_quote_str(token),
+        ))?;
+        Ok(AuthEnv {
+            vars: vec![
+                ("GIT_ASKPASS".to_string(), script.path.display().to_string()),
+                ("GIT_TERMINAL_PROMPT".to_string(), "0".to_string()),
+            ],
+            _askpass_script: Some(script),
+        })
+    }
+}
+
+/// A file deleted from disk when dropped, used to hold credential helper scripts that must not
+/// outlive the git invocation that needs them.
+struct TempFile {
+    path: PathBuf,
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Counter used to keep concurrent `fetch()` calls in this process from racing on the same
+/// askpass script path.
+static ASKPASS_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Write `contents` (a full shell script, shebang included) to a uniquely-named, owner-only,
+/// executable file, for use as a `GIT_ASKPASS`/`SSH_ASKPASS` helper.
+fn create_askpass_script(contents: &str) -> Result<TempFile, Error> {
+    let unique = ASKPASS_COUNTER.fetch_add(1, Ordering::Relaxed);
+    let path = std::env::temp_dir().join(format!(
+        "rustwide-askpass-{}-{}",
+        std::process::id(),
+        unique
+    ));
+
+    // Create the file with its final (owner-only, executable) permissions as part of the
+    // `open()` call itself, and refuse to follow a pre-existing file or symlink at `path` -
+    // otherwise there would be a window where the secret is readable by other local users, or
+    // an attacker could pre-place a symlink to redirect the write.
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o700)
+        .open(&path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(TempFile { path })
+}
+
+fn shell_quote(path: &Path) -> String {
+    shell_quote_str(&path.display().to_string())
+}
+
+fn shell_quote_str(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
@@ -0,0 +1,44 @@
+use crate::crates::CrateTrait;
+use crate::Workspace;
+use failure::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub(super) struct Local {
+    path: PathBuf,
+    pub(super) name: String,
+}
+
+impl Local {
+    pub(super) fn new(path: &Path, name: &str) -> Self {
+        Local {
+            path: path.into(),
+            name: name.into(),
+        }
+    }
+}
+
+impl CrateTrait for Local {
+    fn cache_key(&self) -> String {
+        format!("local/{}", self.path.display())
+    }
+
+    fn fetch(&self, _workspace: &Workspace) -> Result<(), Error> {
+        // Local crates already live on disk, there's nothing to fetch.
+        Ok(())
+    }
+
+    fn purge_from_cache(&self, _workspace: &Workspace) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn copy_source_to(&self, _workspace: &Workspace, dest: &Path) -> Result<(), Error> {
+        crate::utils::copy_dir_all(&self.path, dest)
+    }
+}
+
+impl fmt::Display for Local {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "local crate {}", self.path.display())
+    }
+}
@@ -6,13 +6,39 @@ use crate::Workspace;
 use failure::Error;
 use log::info;
 use std::path::Path;
+use std::time::{Duration, SystemTime};
 
+pub use git::GitAuth;
 pub use registry::AlternativeRegistry;
 
+/// A default threshold (90 days) to pass as `max_age` to [`Crate::fetch_if_stale`]. The method
+/// itself has no built-in default and always uses the `max_age` it is given.
+pub const DEFAULT_MAX_CACHE_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
 trait CrateTrait: std::fmt::Display {
     fn fetch(&self, workspace: &Workspace) -> Result<(), Error>;
     fn purge_from_cache(&self, workspace: &Workspace) -> Result<(), Error>;
     fn copy_source_to(&self, workspace: &Workspace, dest: &Path) -> Result<(), Error>;
+
+    /// A key uniquely identifying where this crate is cached on disk, relative to the
+    /// workspace's cache directory. Two crates that fetch into the same place (and thus can
+    /// share a single fetch) must return the same key; a crate's `Display` impl isn't good
+    /// enough for this, since it doesn't always match the cache destination (e.g. two git crates
+    /// with the same URL but different `name`s cache separately).
+    fn cache_key(&self) -> String;
+
+    /// When this crate's cached source was last fetched, or `None` if it isn't cached or its
+    /// crate type doesn't support staleness tracking.
+    fn cached_at(&self, _workspace: &Workspace) -> Option<SystemTime> {
+        None
+    }
+
+    /// Re-fetch the crate unconditionally, bypassing any "already cached" shortcut `fetch` takes.
+    /// Crate types whose `fetch` is already unconditional (like git repositories, which always
+    /// do an incremental `git fetch`) can just fall back to `fetch`.
+    fn force_fetch(&self, workspace: &Workspace) -> Result<(), Error> {
+        self.fetch(workspace)
+    }
 }
 
 enum CrateType {
@@ -66,6 +92,39 @@ impl Crate {
         ))
     }
 
+    /// Load a crate from a git repository pinned to a specific revision. The rev can be a full
+    /// or abbreviated commit SHA. Unlike [`Crate::git`] and [`Crate::git_branch`], this makes
+    /// fetches reproducible: the same rev is always checked out, regardless of how far the
+    /// remote branch has moved on. The full URL needed to clone the repo has to be provided.
+    pub fn git_rev(url: &str, name: &str, rev: &str) -> Self {
+        Crate(CrateType::Git(git::GitRepo::new(url, name).rev(Some(rev))))
+    }
+
+    /// Load a crate from a git repository pinned to a specific tag. The full URL needed to
+    /// clone the repo has to be provided.
+    pub fn git_tag(url: &str, name: &str, tag: &str) -> Self {
+        Crate(CrateType::Git(git::GitRepo::new(url, name).rev(Some(tag))))
+    }
+
+    /// Authenticate with the remote repository when fetching a private git crate. This has no
+    /// effect on crates loaded from a registry or the local filesystem.
+    pub fn auth(self, auth: GitAuth) -> Self {
+        match self.0 {
+            CrateType::Git(repo) => Crate(CrateType::Git(repo.auth(auth))),
+            other => Crate(other),
+        }
+    }
+
+    /// Recursively fetch and populate this crate's git submodules. Has no effect on crates
+    /// loaded from a registry or the local filesystem. Defaults to off, to preserve the old
+    /// behavior of leaving submodule directories empty.
+    pub fn submodules(self, submodules: bool) -> Self {
+        match self.0 {
+            CrateType::Git(repo) => Crate(CrateType::Git(repo.submodules(submodules))),
+            other => Crate(other),
+        }
+    }
+
     /// Load a crate from a directory in the local filesystem.
     pub fn local(path: &Path, name: &str) -> Self {
         Crate(CrateType::Local(local::Local::new(path, name)))
@@ -92,6 +151,30 @@ impl Crate {
         }
     }
 
+    /// How long it's been since this crate's cached source was last fetched, or `None` if it
+    /// isn't cached yet (or its crate type, like [`Crate::local`], doesn't have a cache).
+    pub fn cache_age(&self, workspace: &Workspace) -> Option<Duration> {
+        SystemTime::now()
+            .duration_since(self.as_trait().cached_at(workspace)?)
+            .ok()
+    }
+
+    /// Fetch the crate only if its cached source is missing or older than `max_age`, otherwise
+    /// do nothing. Registry crates are re-downloaded in full; git repositories are updated with
+    /// an incremental `git fetch` rather than being recloned from scratch.
+    pub fn fetch_if_stale(&self, workspace: &Workspace, max_age: Duration) -> Result<(), Error> {
+        match self.cache_age(workspace) {
+            Some(age) if age <= max_age => Ok(()),
+            _ => self.as_trait().force_fetch(workspace),
+        }
+    }
+
+    /// A key uniquely identifying where this crate is cached in the workspace. Crates that would
+    /// fetch into the same cache location return the same key.
+    pub(crate) fn cache_key(&self) -> String {
+        self.as_trait().cache_key()
+    }
+
     pub(crate) fn copy_source_to(&self, workspace: &Workspace, dest: &Path) -> Result<(), Error> {
         if dest.exists() {
             info!(
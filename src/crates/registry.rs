@@ -0,0 +1,285 @@
+use crate::crates::CrateTrait;
+use crate::Workspace;
+use failure::{bail, format_err, Error, Fail, ResultExt};
+use log::info;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::io::Cursor;
+use std::path::{Component, Path, PathBuf};
+
+/// The downloaded `.crate` tarball didn't match the checksum recorded in the registry index.
+#[derive(Debug, Fail)]
+#[fail(
+    display = "checksum mismatch for {} {}: expected {}, got {}",
+    name, version, expected, actual
+)]
+pub struct ChecksumMismatch {
+    /// Name of the crate that failed verification.
+    pub name: String,
+    /// Version of the crate that failed verification.
+    pub version: String,
+    /// Checksum recorded in the registry index.
+    pub expected: String,
+    /// Checksum computed from the downloaded tarball.
+    pub actual: String,
+}
+
+/// A registry alternative to [crates.io](https://crates.io).
+pub struct AlternativeRegistry {
+    index: String,
+}
+
+impl AlternativeRegistry {
+    /// Load an alternative registry, given the URL of its index.
+    pub fn new(index: &str) -> Self {
+        AlternativeRegistry {
+            index: index.to_string(),
+        }
+    }
+}
+
+pub(super) enum Registry {
+    CratesIo,
+    Alternative(AlternativeRegistry),
+}
+
+impl Registry {
+    fn download_url(&self, name: &str, version: &str) -> String {
+        match self {
+            Registry::CratesIo => format!(
+                "https://crates.io/api/v1/crates/{}/{}/download",
+                name, version
+            ),
+            Registry::Alternative(alt) => {
+                format!("{}/{}/{}/download", alt.index, name, version)
+            }
+        }
+    }
+
+    /// URL of the index entry listing every published version of a crate, in the sparse index
+    /// layout used by crates.io and cargo's alternative registry protocol.
+    fn index_url(&self, name: &str) -> String {
+        let path = match name.len() {
+            1 => format!("1/{}", name),
+            2 => format!("2/{}", name),
+            3 => format!("3/{}/{}", &name[..1], name),
+            _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+        };
+        match self {
+            Registry::CratesIo => format!("https://index.crates.io/{}", path),
+            Registry::Alternative(alt) => format!("{}/{}", alt.index, path),
+        }
+    }
+
+    /// Look up the expected SHA-256 checksum of a crate's tarball from the registry index.
+    fn cksum(&self, name: &str, version: &str) -> Result<String, Error> {
+        let url = self.index_url(name);
+        let index = reqwest::blocking::get(&url)
+            .with_context(|_| format!("failed to fetch the registry index at {}", url))?
+            .text()
+            .with_context(|_| format!("failed to read the registry index at {}", url))?;
+
+        for line in index.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: serde_json::Value = serde_json::from_str(line)
+                .with_context(|_| format!("failed to parse the registry index at {}", url))?;
+            if entry["vers"].as_str() == Some(version) {
+                return entry["cksum"]
+                    .as_str()
+                    .map(|s| s.to_string())
+                    .ok_or_else(|| {
+                        format_err!("index entry for {} {} has no cksum", name, version)
+                    });
+            }
+        }
+
+        Err(format_err!("no index entry found for {} {}", name, version))
+    }
+}
+
+pub(super) struct RegistryCrate {
+    registry: Registry,
+    pub(super) name: String,
+    version: String,
+}
+
+impl RegistryCrate {
+    pub(super) fn new(registry: Registry, name: &str, version: &str) -> Self {
+        RegistryCrate {
+            registry,
+            name: name.to_string(),
+            version: version.to_string(),
+        }
+    }
+
+    fn cache_path(&self, workspace: &Workspace) -> PathBuf {
+        workspace.cache_dir().join(self.cache_key())
+    }
+}
+
+impl CrateTrait for RegistryCrate {
+    fn cache_key(&self) -> String {
+        format!("registry-crates/{}-{}.crate", self.name, self.version)
+    }
+
+    fn fetch(&self, workspace: &Workspace) -> Result<(), Error> {
+        let dest = self.cache_path(workspace);
+        if dest.exists() {
+            return Ok(());
+        }
+
+        info!(
+            "fetching crate {} {} from the registry",
+            self.name, self.version
+        );
+        let url = self.registry.download_url(&self.name, &self.version);
+        let content = reqwest::blocking::get(&url)
+            .with_context(|_| format!("failed to fetch {}", url))?
+            .bytes()
+            .with_context(|_| format!("failed to read the response body of {}", url))?;
+
+        let expected = self.registry.cksum(&self.name, &self.version)?;
+        let actual = format!("{:x}", Sha256::digest(&content));
+        if actual != expected {
+            return Err(ChecksumMismatch {
+                name: self.name.clone(),
+                version: self.version.clone(),
+                expected,
+                actual,
+            }
+            .into());
+        }
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&dest, &content)?;
+        Ok(())
+    }
+
+    fn force_fetch(&self, workspace: &Workspace) -> Result<(), Error> {
+        let dest = self.cache_path(workspace);
+        if dest.exists() {
+            fs::remove_file(&dest)?;
+        }
+        self.fetch(workspace)
+    }
+
+    fn cached_at(&self, workspace: &Workspace) -> Option<std::time::SystemTime> {
+        fs::metadata(self.cache_path(workspace))
+            .and_then(|metadata| metadata.modified())
+            .ok()
+    }
+
+    fn purge_from_cache(&self, workspace: &Workspace) -> Result<(), Error> {
+        let dest = self.cache_path(workspace);
+        if dest.exists() {
+            fs::remove_file(&dest)?;
+        }
+        Ok(())
+    }
+
+    fn copy_source_to(&self, workspace: &Workspace, dest: &Path) -> Result<(), Error> {
+        let content = fs::read(self.cache_path(workspace))?;
+        let tar = flate2::read::GzDecoder::new(Cursor::new(content));
+        let mut archive = tar::Archive::new(tar);
+
+        fs::create_dir_all(dest)?;
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            // The downloaded tarball wraps its contents in a `name-version/` directory, which we
+            // don't want to replicate in the destination.
+            let relative = path.components().skip(1).collect::<PathBuf>();
+            // Refuse to extract entries that would escape `dest`: a malicious or corrupted
+            // tarball could otherwise smuggle a `../` or absolute path (CWE-22/"Zip Slip").
+            if relative
+                .components()
+                .any(|c| !matches!(c, Component::Normal(_)))
+            {
+                bail!(
+                    "crate {} {} contains an unsafe tarball entry path: {}",
+                    self.name,
+                    self.version,
+                    path.display()
+                );
+            }
+            let entry_dest = dest.join(&relative);
+            if let Some(parent) = entry_dest.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(&entry_dest)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for RegistryCrate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "crate {} {}", self.name, self.version)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn index_url_branches_on_name_length() {
+        assert_eq!(
+            Registry::CratesIo.index_url("a"),
+            "https://index.crates.io/1/a"
+        );
+        assert_eq!(
+            Registry::CratesIo.index_url("ab"),
+            "https://index.crates.io/2/ab"
+        );
+        assert_eq!(
+            Registry::CratesIo.index_url("abc"),
+            "https://index.crates.io/3/a/abc"
+        );
+        assert_eq!(
+            Registry::CratesIo.index_url("serde"),
+            "https://index.crates.io/se/rd/serde"
+        );
+    }
+
+    #[test]
+    fn index_url_uses_the_alternative_registry_base() {
+        let registry = Registry::Alternative(AlternativeRegistry::new("https://my-registry.test"));
+        assert_eq!(
+            registry.index_url("serde"),
+            "https://my-registry.test/se/rd/serde"
+        );
+    }
+
+    #[test]
+    fn checksum_mismatch_reports_both_checksums() {
+        let err = ChecksumMismatch {
+            name: "foo".to_string(),
+            version: "1.0.0".to_string(),
+            expected: "aaaa".to_string(),
+            actual: "bbbb".to_string(),
+        };
+        assert_eq!(
+            err.to_string(),
+            "checksum mismatch for foo 1.0.0: expected aaaa, got bbbb"
+        );
+    }
+
+    /// Mirrors the unsafe-path check in `RegistryCrate::copy_source_to`: only tarball entries
+    /// made up entirely of `Component::Normal` parts are safe to extract.
+    fn is_safe_relative_path(path: &Path) -> bool {
+        path.components().all(|c| matches!(c, Component::Normal(_)))
+    }
+
+    #[test]
+    fn rejects_tarball_entries_that_escape_the_destination() {
+        assert!(is_safe_relative_path(Path::new("src/lib.rs")));
+        assert!(!is_safe_relative_path(Path::new("../../etc/passwd")));
+        assert!(!is_safe_relative_path(Path::new("/etc/passwd")));
+    }
+}
@@ -0,0 +1,127 @@
+//! rustwide provides an easy to use API to execute compiling actions for an arbitrary crate,
+//! either present on `crates.io` or in a local directory, collecting the results into a
+//! sandboxed workspace.
+
+pub mod crates;
+pub(crate) mod utils;
+
+use crate::crates::Crate;
+use failure::{format_err, Error};
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// A workspace is where all the data, caches and crate sources used by rustwide are stored.
+pub struct Workspace {
+    cache_dir: PathBuf,
+}
+
+impl Workspace {
+    /// Open a workspace rooted at the given directory, creating it if it doesn't already exist.
+    pub fn new(cache_dir: impl Into<PathBuf>) -> Self {
+        Workspace {
+            cache_dir: cache_dir.into(),
+        }
+    }
+
+    /// The directory rustwide uses to cache crate sources and registry indices.
+    pub fn cache_dir(&self) -> &Path {
+        &self.cache_dir
+    }
+
+    /// Fetch many crates concurrently, using at most `concurrency` workers. Crates with an
+    /// identical source (for example the same registry crate listed twice) are only fetched
+    /// once, and a single crate failing to fetch doesn't prevent the rest from being fetched.
+    ///
+    /// Returns one result per input crate, in the same order `crates` was provided in.
+    pub fn fetch_all<'a>(
+        &self,
+        crates: &'a [Crate],
+        concurrency: usize,
+    ) -> Vec<(&'a Crate, Result<(), Error>)> {
+        let mut by_source: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, krate) in crates.iter().enumerate() {
+            by_source.entry(krate.cache_key()).or_default().push(index);
+        }
+
+        let results: Mutex<Vec<Option<Result<(), Error>>>> =
+            Mutex::new((0..crates.len()).map(|_| None).collect());
+
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(concurrency.max(1))
+            .build()
+            .expect("failed to create the fetch thread pool");
+        pool.install(|| {
+            by_source.par_iter().for_each(|(_, indexes)| {
+                let outcome = crates[indexes[0]].fetch(self);
+                let mut results = results.lock().unwrap();
+                for &index in indexes {
+                    results[index] = Some(match &outcome {
+                        Ok(()) => Ok(()),
+                        Err(err) => Err(format_err!("{}", err)),
+                    });
+                }
+            });
+        });
+
+        crates
+            .iter()
+            .zip(results.into_inner().unwrap())
+            .map(|(krate, result)| {
+                (
+                    krate,
+                    result.expect("every crate should have a fetch result"),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn fetch_all_returns_one_result_per_crate_in_order() {
+        let workspace = Workspace::new(std::env::temp_dir().join("rustwide-fetch-all-test"));
+        let crates = vec![
+            Crate::local(Path::new("."), "a"),
+            Crate::local(Path::new("."), "b"),
+            Crate::local(Path::new("."), "a"),
+        ];
+
+        let results = workspace.fetch_all(&crates, 2);
+
+        assert_eq!(results.len(), crates.len());
+        let names: Vec<&str> = results.iter().map(|(krate, _)| krate.name()).collect();
+        assert_eq!(names, vec!["a", "b", "a"]);
+        for (krate, result) in &results {
+            assert!(result.is_ok(), "fetch of {} failed: {:?}", krate, result);
+        }
+    }
+
+    #[test]
+    fn crates_sharing_a_cache_key_are_grouped_into_one_fetch() {
+        let crates = vec![
+            Crate::local(Path::new("same/path"), "one-name"),
+            Crate::local(Path::new("same/path"), "another-name"),
+            Crate::local(Path::new("different/path"), "one-name"),
+        ];
+
+        let mut by_source: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, krate) in crates.iter().enumerate() {
+            by_source.entry(krate.cache_key()).or_default().push(index);
+        }
+
+        // The two crates at "same/path" cache to the same place (cache_key only depends on the
+        // path, not the name given to `Crate::local`), so they must land in the same group.
+        assert_eq!(by_source.len(), 2);
+        let same_path_group = by_source
+            .values()
+            .find(|indexes| indexes.len() == 2)
+            .expect("expected one group with two crates");
+        assert_eq!(same_path_group, &vec![0, 1]);
+    }
+}
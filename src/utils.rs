@@ -0,0 +1,22 @@
+use failure::Error;
+use std::fs;
+use std::path::Path;
+
+pub(crate) fn remove_dir_all(path: &Path) -> Result<(), Error> {
+    fs::remove_dir_all(path)?;
+    Ok(())
+}
+
+pub(crate) fn copy_dir_all(src: &Path, dest: &Path) -> Result<(), Error> {
+    fs::create_dir_all(dest)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let entry_dest = dest.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir_all(&entry.path(), &entry_dest)?;
+        } else {
+            fs::copy(entry.path(), &entry_dest)?;
+        }
+    }
+    Ok(())
+}